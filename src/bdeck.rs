@@ -0,0 +1,220 @@
+//! Parsing of individual ATCF b-deck records.
+//!
+//! A single malformed or truncated line should never abort a whole run, so
+//! every record is parsed independently and failures are reported as a
+//! `ParseError` rather than a panic.
+
+use std::fmt;
+
+/// A single failed record, with enough context to locate and explain it.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub path: String,
+    pub line_number: usize,
+    pub byte_range: (usize, usize),
+    pub reason: String,
+}
+
+impl ParseError {
+    fn new(path: &str, line_number: usize, byte_range: (usize, usize), reason: impl Into<String>) -> Self {
+        ParseError {
+            path: path.to_string(),
+            line_number,
+            byte_range,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} (bytes {}..{}): {}",
+            self.path, self.line_number, self.byte_range.0, self.byte_range.1, self.reason
+        )
+    }
+}
+
+/// One parsed storm fix (a single b-deck line).
+#[derive(Debug, Clone)]
+pub struct StormFix {
+    pub atcf_basin: String,
+    pub atcf_number: String,
+    /// Raw `YYYYMMDDHH` field, used to de-duplicate repeated fixes.
+    pub time_str: String,
+    pub year: i32,
+    pub month: i32,
+    pub hour: i32,
+    pub wind: i32,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub storm_type: String,
+}
+
+/// Parse one b-deck line, returning a descriptive `ParseError` instead of
+/// panicking on short lines or non-numeric fields.
+pub fn parse_line(path: &str, line_number: usize, line: &str) -> Result<StormFix, ParseError> {
+    if line.len() < 46 {
+        return Err(ParseError::new(
+            path,
+            line_number,
+            (0, line.len()),
+            "line shorter than 46 bytes",
+        ));
+    }
+    // b-deck lines are ASCII-only; reject anything else up front so the
+    // fixed-byte-offset slicing below can never land on a non-char boundary
+    // and panic instead of returning a `ParseError`.
+    if !line.is_ascii() {
+        return Err(ParseError::new(
+            path,
+            line_number,
+            (0, line.len()),
+            "line contains non-ASCII bytes",
+        ));
+    }
+    let atcf_basin = line[0..2].to_string();
+    let atcf_number = line
+        .get(4..6)
+        .ok_or_else(|| ParseError::new(path, line_number, (4, 6), "storm number field out of range"))?
+        .to_string();
+
+    let time_str = line
+        .get(8..18)
+        .ok_or_else(|| ParseError::new(path, line_number, (8, 18), "date/time field out of range"))?
+        .to_string();
+    let year: i32 = time_str[..4]
+        .parse()
+        .map_err(|_| ParseError::new(path, line_number, (8, 12), "year field not numeric"))?;
+    let month: i32 = time_str[4..6]
+        .parse()
+        .map_err(|_| ParseError::new(path, line_number, (12, 14), "month field not numeric"))?;
+    let hour: i32 = time_str[8..10]
+        .parse()
+        .map_err(|_| ParseError::new(path, line_number, (16, 18), "hour field not numeric"))?;
+
+    let line_len = line.len() - 1;
+    let temp_wind = if line_len < 51 {
+        if line_len < 3 {
+            return Err(ParseError::new(
+                path,
+                line_number,
+                (0, line.len()),
+                "line too short to contain a wind field",
+            ));
+        }
+        &line[line_len - 3..]
+    } else {
+        &line[48..51]
+    };
+    let mut wind: i32 = temp_wind
+        .strip_prefix(' ')
+        .unwrap_or(temp_wind)
+        .parse()
+        .map_err(|_| ParseError::new(path, line_number, (48, 51), "wind field not numeric"))?;
+    if wind == 999 {
+        wind = 0;
+    }
+
+    let lat_str = line
+        .get(35..39)
+        .ok_or_else(|| ParseError::new(path, line_number, (35, 39), "latitude field out of range"))?;
+    let lat_string: String = lat_str[..3].chars().filter(|c| !c.is_whitespace()).collect();
+    let mut latitude: f32 = lat_string
+        .parse::<f32>()
+        .map_err(|_| ParseError::new(path, line_number, (35, 38), "latitude field not numeric"))?
+        / 10.;
+    if &lat_str[3..4] == "S" {
+        latitude *= -1.;
+    }
+
+    let lon_str = line
+        .get(41..46)
+        .ok_or_else(|| ParseError::new(path, line_number, (41, 46), "longitude field out of range"))?;
+    let lon_string: String = lon_str[..4].chars().filter(|c| !c.is_whitespace()).collect();
+    let mut longitude: f32 = lon_string
+        .parse::<f32>()
+        .map_err(|_| ParseError::new(path, line_number, (41, 45), "longitude field not numeric"))?
+        / 10.;
+    if &lon_str[4..5] == "W" {
+        longitude = 360. - longitude;
+    }
+
+    let mut storm_type = String::new();
+    if line_len > 59 {
+        storm_type = line
+            .get(59..61)
+            .ok_or_else(|| ParseError::new(path, line_number, (59, 61), "storm type field out of range"))?
+            .to_string();
+    }
+
+    Ok(StormFix {
+        atcf_basin,
+        atcf_number,
+        time_str,
+        year,
+        month,
+        hour,
+        wind,
+        latitude,
+        longitude,
+        storm_type,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_LINE: &str =
+        "AL  01  2020010100                 123N   456W   40        TD         ";
+
+    #[test]
+    fn parses_a_well_formed_line() {
+        let fix = parse_line("test.dat", 1, VALID_LINE).unwrap();
+        assert_eq!(fix.atcf_basin, "AL");
+        assert_eq!(fix.atcf_number, "01");
+        assert_eq!(fix.year, 2020);
+        assert_eq!(fix.month, 1);
+        assert_eq!(fix.hour, 0);
+        assert_eq!(fix.wind, 40);
+        assert_eq!(fix.storm_type, "TD");
+    }
+
+    #[test]
+    fn rejects_a_line_that_is_too_short() {
+        let err = parse_line("test.dat", 5, "AL, 01, 2020010100").unwrap_err();
+        assert_eq!(err.path, "test.dat");
+        assert_eq!(err.line_number, 5);
+        assert!(err.reason.contains("shorter than 46 bytes"));
+    }
+
+    #[test]
+    fn rejects_a_non_numeric_year_field() {
+        let mut bytes = VALID_LINE.as_bytes().to_vec();
+        bytes[8..12].copy_from_slice(b"YYYY");
+        let line = String::from_utf8(bytes).unwrap();
+        let err = parse_line("test.dat", 1, &line).unwrap_err();
+        assert!(err.reason.contains("year field not numeric"));
+    }
+
+    #[test]
+    fn rejects_a_non_numeric_wind_field() {
+        let mut bytes = VALID_LINE.as_bytes().to_vec();
+        bytes[48..51].copy_from_slice(b"XXX");
+        let line = String::from_utf8(bytes).unwrap();
+        let err = parse_line("test.dat", 1, &line).unwrap_err();
+        assert!(err.reason.contains("wind field not numeric"));
+    }
+
+    #[test]
+    fn rejects_a_non_ascii_line_instead_of_panicking() {
+        // A multi-byte character straddling a fixed byte offset used to
+        // panic with "byte index is not a char boundary"; it must now
+        // produce an ordinary `ParseError` instead.
+        let line = format!("A\u{e9}{}", "X".repeat(60));
+        let err = parse_line("test.dat", 1, &line).unwrap_err();
+        assert!(err.reason.contains("non-ASCII"));
+    }
+}
@@ -1,74 +1,124 @@
-use clap::{CommandFactory, Parser};
-use glob::glob;
+mod bdeck;
+
+use clap::{CommandFactory, Parser, ValueEnum};
+use glob::{glob, Pattern};
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
+use std::path::Path;
+
+/// Accumulated cyclone activity metrics for one basin: ACE, PDI, and
+/// threshold counts, all derived from the same per-storm synoptic loop.
+#[derive(Default, Debug, Clone, Copy)]
+struct BasinMetrics {
+    /// Sum of v^2 over 6-hourly synoptic times at >=35kt, in raw kt^2
+    /// (scaled by 10^-4 when displayed).
+    ace: i32,
+    /// Sum of v^3 over the same synoptic times, in raw kt^3 (scaled by
+    /// 10^-4 when displayed).
+    pdi: i64,
+    /// Storms that reached named-storm strength (peak >=34kt).
+    storms: i32,
+    /// Storms that reached hurricane/typhoon strength (peak >=64kt).
+    hurricanes: i32,
+    /// Storms that reached major/intense strength (peak >=96kt).
+    major: i32,
+}
+
+impl BasinMetrics {
+    /// Whether this basin has anything worth reporting — a storm may reach
+    /// named-storm strength only at a non-synoptic hour, leaving `ace`/`pdi`
+    /// at zero while `storms` is still 1, so presence can't be judged by
+    /// `ace` alone.
+    fn has_activity(&self) -> bool {
+        self.ace > 0 || self.pdi > 0 || self.storms > 0 || self.hurricanes > 0 || self.major > 0
+    }
+}
 
 #[derive(Default, Debug)]
-struct PerBasinACE {
-    wpac: i32,
-    nio: i32,
-    shem: i32,
-    epac: i32,
-    atl: i32,
+struct PerBasinMetrics {
+    wpac: BasinMetrics,
+    nio: BasinMetrics,
+    shem: BasinMetrics,
+    epac: BasinMetrics,
+    atl: BasinMetrics,
 }
 
-impl PerBasinACE {
-    fn sum(&self) -> i32 {
-        self.to_array().iter().sum()
+impl PerBasinMetrics {
+    fn sum_ace(&self) -> i32 {
+        self.to_array().iter().map(|m| m.ace).sum()
     }
 
-    fn to_array(&self) -> [i32; 5] {
+    fn to_array(&self) -> [BasinMetrics; 5] {
         [self.wpac, self.nio, self.shem, self.epac, self.atl]
     }
 
-    fn update_ace(&mut self, basin: &Basin, ace: i32) {
+    /// Basin name paired with its metrics, in display order.
+    fn named_values(&self) -> [(&'static str, BasinMetrics); 5] {
+        [
+            ("wpac", self.wpac),
+            ("epac", self.epac),
+            ("atl", self.atl),
+            ("shem", self.shem),
+            ("nio", self.nio),
+        ]
+    }
+
+    fn basin_mut(&mut self, basin: &Basin) -> &mut BasinMetrics {
         match basin {
-            Basin::WPAC => self.wpac += ace,
-            Basin::EPAC => self.epac += ace,
-            Basin::NIO => self.nio += ace,
-            Basin::SHEM => self.shem += ace,
-            Basin::ATL => self.atl += ace,
+            Basin::WPAC => &mut self.wpac,
+            Basin::EPAC => &mut self.epac,
+            Basin::NIO => &mut self.nio,
+            Basin::SHEM => &mut self.shem,
+            Basin::ATL => &mut self.atl,
+        }
+    }
+
+    /// Accumulate ACE/PDI for one >=35kt synoptic fix.
+    fn update_synoptic(&mut self, basin: &Basin, wind: i32) {
+        let m = self.basin_mut(basin);
+        m.ace += wind.pow(2);
+        m.pdi += (wind as i64).pow(3);
+    }
+
+    /// Record a finished storm's peak intensity against the basin(s) it touched.
+    fn record_storm(&mut self, basin: &Basin, max_wind: i32) {
+        let m = self.basin_mut(basin);
+        m.storms += 1;
+        if max_wind >= 64 {
+            m.hurricanes += 1;
+        }
+        if max_wind >= 96 {
+            m.major += 1;
         }
     }
 
     fn basin_count(&self) -> i32 {
         let mut count: i32 = 0;
-        for var in self.to_array() {
-            if var > 0 {
+        for m in self.to_array() {
+            if m.has_activity() {
                 count += 1;
             }
         }
         count
     }
 
-    fn summarize(&self, separator: &str) -> String {
-        let mut text = "".to_string();
-        if self.wpac > 0 {
-            text += &format!("WPAC: {:.4}", self.wpac as f32 / 10000.);
-            text += separator;
-        }
-        if self.epac > 0 {
-            text += &format!("ECPAC: {:.4}", self.epac as f32 / 10000.);
-            text += separator;
-        }
-        if self.atl > 0 {
-            text += &format!("ATL: {:.4}", self.atl as f32 / 10000.);
-            text += separator;
-        }
-        if self.shem > 0 {
-            text += &format!("SHEM: {:.4}", self.shem as f32 / 10000.);
-            text += separator;
-        }
-        if self.nio > 0 {
-            text += &format!("NIO: {:.4}", self.nio as f32 / 10000.);
-        }
-        text.strip_suffix(separator).unwrap_or(&text).to_string()
+    fn has_activity(&self) -> bool {
+        self.to_array().iter().any(BasinMetrics::has_activity)
     }
 
-    fn print_perbasin_ace(&self) {
-        print!("     Per basin ACE: ");
-        print!("{}\n", self.summarize("  "));
+    fn summarize(&self, separator: &str, metrics: &[Metric]) -> String {
+        self.named_values()
+            .iter()
+            .filter(|(_, m)| m.has_activity())
+            .map(|(key, m)| format_basin_metrics(basin_label(key), m, key, metrics))
+            .collect::<Vec<String>>()
+            .join(separator)
+    }
+
+    fn print_perbasin(&self, metrics: &[Metric]) {
+        print!("     Per basin metrics: ");
+        println!("{}", self.summarize("  ", metrics));
     }
 }
 
@@ -76,9 +126,10 @@ impl PerBasinACE {
 struct StormStats {
     atcf_code: String,
     max_wind: i32,
-    ace: PerBasinACE,
+    metrics: PerBasinMetrics,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum Basin {
     WPAC,
     NIO,
@@ -93,165 +144,508 @@ fn is_tropical(storm_type: &str) -> bool {
     return !non_tropical.contains(&storm_type);
 }
 
-fn is_synop_time(time_str: &str) -> bool {
-    let t: i32 = time_str.parse().unwrap();
-    return (t % 6) == 0;
+fn is_synop_time(hour: i32) -> bool {
+    return (hour % 6) == 0;
+}
+
+/// Longitude (°E, 0-360) west of which a storm in the 100-300° band is
+/// unambiguously East Pacific, clear of the Central American isthmus.
+const EPAC_MERIDIAN_LONGITUDE: f32 = 240.0; // 120°W
+/// Longitude (°E, 0-360) east of which a storm in the 100-300° band is
+/// unambiguously Atlantic, clear of the isthmus.
+const ATL_MERIDIAN_LONGITUDE: f32 = 300.0; // 60°W
+
+/// Reference points (latitude °N, longitude °E) approximating the NHC
+/// boundary between the Atlantic and East Pacific basins as it follows the
+/// Central American isthmus, rather than a single meridian. East of the
+/// diagonal connecting them is Atlantic; west is East Pacific. These are
+/// intentionally tunable rather than buried in the comparison logic.
+const ISTHMUS_NORTH: (f32, f32) = (15.0, 261.5); // ~15°N, 98.5°W: southern Mexico coast
+const ISTHMUS_SOUTH: (f32, f32) = (8.0, 283.0); // ~8°N, 77°W: Panama/Colombia border
+
+/// Is (latitude, longitude) east of the Atlantic/East-Pacific isthmus line?
+fn is_east_of_isthmus(latitude: f32, longitude: f32) -> bool {
+    let (south_lat, south_lon) = ISTHMUS_SOUTH;
+    let (north_lat, north_lon) = ISTHMUS_NORTH;
+    if latitude <= south_lat {
+        return longitude > south_lon;
+    }
+    if latitude >= north_lat {
+        return longitude > north_lon;
+    }
+    let t = (latitude - south_lat) / (north_lat - south_lat);
+    let boundary_longitude = south_lon + t * (north_lon - south_lon);
+    longitude > boundary_longitude
 }
 
-fn get_basin(latitude: f32, longitude: f32) -> Basin {
+/// Basin assignment from lat/lon alone, used only when the ATCF basin code
+/// doesn't already tell us unambiguously.
+fn get_basin_by_geography(latitude: f32, longitude: f32) -> Basin {
     if latitude < 0. {
         return Basin::SHEM;
     }
     if longitude < 100. {
         if latitude < 40. {
             return Basin::NIO;
+        } else if longitude < 70.0 {
+            return Basin::ATL;
         } else {
-            if longitude < 70.0 {
-                return Basin::ATL;
-            } else {
-                return Basin::WPAC;
-            }
+            return Basin::WPAC;
         }
     } else if longitude <= 180. {
         return Basin::WPAC;
+    } else if longitude < EPAC_MERIDIAN_LONGITUDE {
+        return Basin::EPAC;
+    } else if longitude > ATL_MERIDIAN_LONGITUDE {
+        return Basin::ATL;
+    } else if is_east_of_isthmus(latitude, longitude) {
+        return Basin::ATL;
     } else {
-        if longitude < 240. {
-            return Basin::EPAC;
-        } else if longitude > 300. {
-            return Basin::ATL;
-        } else {
-            // Complex boundary between EPAC and NATL, return EPAC for now.
-            return Basin::EPAC;
-        }
+        return Basin::EPAC;
+    }
+}
+
+/// Basin assignment for a storm fix. The ATCF basin code from the file
+/// (`AL`, `EP`/`CP`, `WP`, `IO`, `SH`) is authoritative when recognized;
+/// geography is only a fallback for ambiguous or unrecognized codes.
+fn get_basin(atcf_basin: &str, latitude: f32, longitude: f32) -> Basin {
+    match atcf_basin {
+        "AL" => Basin::ATL,
+        "EP" | "CP" => Basin::EPAC,
+        "WP" => Basin::WPAC,
+        "IO" => Basin::NIO,
+        "SH" => Basin::SHEM,
+        _ => get_basin_by_geography(latitude, longitude),
+    }
+}
+
+/// Display label for a basin key, matching the original per-basin report.
+fn basin_label(basin_key: &str) -> &'static str {
+    match basin_key {
+        "wpac" => "WPAC",
+        "epac" => "ECPAC",
+        "atl" => "ATL",
+        "shem" => "SHEM",
+        "nio" => "NIO",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Approximate climatological (1950-2000 era) per-basin averages used as the
+/// baseline for the Net Tropical Cyclone (NTC) index. Like the isthmus
+/// boundary above, these are tunable placeholders rather than precise
+/// figures: NTC is a percentage relative to them, not an absolute count.
+struct BasinClimatology {
+    storms: f32,
+    hurricanes: f32,
+    major: f32,
+    ace: f32, // same 10^-4 kt^2 units as displayed ACE
+}
+
+fn climatology_for(basin_key: &str) -> BasinClimatology {
+    match basin_key {
+        "wpac" => BasinClimatology {
+            storms: 26.5,
+            hurricanes: 16.3,
+            major: 8.3,
+            ace: 295.5,
+        },
+        "epac" => BasinClimatology {
+            storms: 15.4,
+            hurricanes: 7.8,
+            major: 3.3,
+            ace: 131.8,
+        },
+        "atl" => BasinClimatology {
+            storms: 9.8,
+            hurricanes: 5.9,
+            major: 2.3,
+            ace: 96.1,
+        },
+        "shem" => BasinClimatology {
+            storms: 20.0,
+            hurricanes: 9.0,
+            major: 3.0,
+            ace: 120.0,
+        },
+        _ => BasinClimatology {
+            storms: 5.0,
+            hurricanes: 2.0,
+            major: 0.5,
+            ace: 13.8,
+        },
     }
-    //panic!("Incorrect coordinates");
 }
 
-fn print_ace(ace_map: HashMap<i32, PerBasinACE>) {
+/// Net Tropical Cyclone style index: the average, as a percentage, of how a
+/// basin's storm/hurricane/major counts and ACE compare to climatology.
+fn ntc_percent(basin_key: &str, m: &BasinMetrics) -> f32 {
+    let c = climatology_for(basin_key);
+    let ace_scaled = m.ace as f32 / 10000.;
+    let ratio_sum =
+        (m.storms as f32 / c.storms) + (m.hurricanes as f32 / c.hurricanes) + (m.major as f32 / c.major) + (ace_scaled / c.ace);
+    ratio_sum / 4.0 * 100.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Metric {
+    Ace,
+    Pdi,
+    Storms,
+    Hurricanes,
+    Major,
+    Ntc,
+}
+
+fn metric_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Ace => "ace",
+        Metric::Pdi => "pdi",
+        Metric::Storms => "storms",
+        Metric::Hurricanes => "hurricanes",
+        Metric::Major => "major",
+        Metric::Ntc => "ntc",
+    }
+}
+
+/// Render one metric for one basin as (field name, value text).
+fn metric_value(metric: Metric, m: &BasinMetrics, basin_key: &str) -> (&'static str, String) {
+    match metric {
+        Metric::Ace => (metric_name(metric), format!("{:.4}", m.ace as f32 / 10000.)),
+        Metric::Pdi => (metric_name(metric), format!("{:.4}", m.pdi as f32 / 10000.)),
+        Metric::Storms => (metric_name(metric), format!("{}", m.storms)),
+        Metric::Hurricanes => (metric_name(metric), format!("{}", m.hurricanes)),
+        Metric::Major => (metric_name(metric), format!("{}", m.major)),
+        Metric::Ntc => (metric_name(metric), format!("{:.1}", ntc_percent(basin_key, m))),
+    }
+}
+
+fn format_basin_metrics(label: &str, m: &BasinMetrics, basin_key: &str, metrics: &[Metric]) -> String {
+    if metrics.len() == 1 && metrics[0] == Metric::Ace {
+        return format!("{}: {:.4}", label, m.ace as f32 / 10000.);
+    }
+    let parts: Vec<String> = metrics
+        .iter()
+        .map(|metric| {
+            let (name, value) = metric_value(*metric, m, basin_key);
+            format!("{}={}", name.to_uppercase(), value)
+        })
+        .collect();
+    format!("{}: {}", label, parts.join(" "))
+}
+
+fn print_ace(ace_map: &HashMap<i32, PerBasinMetrics>, metrics: &[Metric]) {
     println!("{}", "--------Summary--------");
-    for year in ace_map.keys() {
+    let mut years: Vec<&i32> = ace_map.keys().collect();
+    years.sort();
+    for year in years {
         let tmp = ace_map.get(year).unwrap();
-        if tmp.sum() > 0 {
+        if tmp.has_activity() {
             println!("{}: ", year);
-            println!("{}", tmp.summarize("\n"));
+            println!("{}", tmp.summarize("\n", metrics));
+        }
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn metric_columns(metrics: &[Metric]) -> Vec<String> {
+    let mut cols = Vec::new();
+    for metric in metrics {
+        for (basin_key, _) in PerBasinMetrics::default().named_values() {
+            cols.push(format!("{}_{}", metric_name(*metric), basin_key));
+        }
+    }
+    cols
+}
+
+fn csv_row(metrics_map: &PerBasinMetrics, metrics: &[Metric]) -> Vec<String> {
+    let mut cols = Vec::new();
+    for metric in metrics {
+        for (basin_key, m) in metrics_map.named_values() {
+            let (_, value) = metric_value(*metric, &m, basin_key);
+            cols.push(value);
+        }
+    }
+    cols
+}
+
+fn basin_metrics_json(metrics_map: &PerBasinMetrics, metrics: &[Metric]) -> String {
+    let entries: Vec<String> = metrics_map
+        .named_values()
+        .iter()
+        .filter(|(_, m)| m.has_activity())
+        .map(|(basin_key, m)| {
+            let fields: Vec<String> = metrics
+                .iter()
+                .map(|metric| {
+                    let (name, value) = metric_value(*metric, m, basin_key);
+                    format!("\"{}\":{}", name, value)
+                })
+                .collect();
+            format!("\"{}\":{{{}}}", basin_key, fields.join(","))
+        })
+        .collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+fn render_json(storm_stats: &[StormStats], ace_map: &HashMap<i32, PerBasinMetrics>, metrics: &[Metric]) -> String {
+    let storms: Vec<String> = storm_stats
+        .iter()
+        .map(|ss| {
+            format!(
+                "{{\"atcf_code\":\"{}\",\"max_wind\":{},\"ace\":{:.4},\"per_basin_metrics\":{}}}",
+                escape_json(&ss.atcf_code),
+                ss.max_wind,
+                ss.metrics.sum_ace() as f32 / 10000.,
+                basin_metrics_json(&ss.metrics, metrics)
+            )
+        })
+        .collect();
+
+    let mut years: Vec<&i32> = ace_map.keys().collect();
+    years.sort();
+    let yearly: Vec<String> = years
+        .iter()
+        .filter(|year| ace_map[year].has_activity())
+        .map(|year| format!("\"{}\":{}", year, basin_metrics_json(&ace_map[year], metrics)))
+        .collect();
+
+    format!(
+        "{{\"storms\":[{}],\"yearly_summary\":{{{}}}}}",
+        storms.join(","),
+        yearly.join(",")
+    )
+}
+
+fn render_csv(
+    storm_stats: &[StormStats],
+    ace_map: &HashMap<i32, PerBasinMetrics>,
+    split: bool,
+    metrics: &[Metric],
+) -> String {
+    let cols = metric_columns(metrics);
+    let mut out = format!("atcf_code,max_wind,{}\n", cols.join(","));
+    for ss in storm_stats {
+        out += &format!(
+            "{},{},{}\n",
+            ss.atcf_code,
+            ss.max_wind,
+            csv_row(&ss.metrics, metrics).join(",")
+        );
+    }
+
+    if split {
+        out += &format!("\nyear,{}\n", cols.join(","));
+        let mut years: Vec<&i32> = ace_map.keys().collect();
+        years.sort();
+        for year in years {
+            let tmp = &ace_map[year];
+            if tmp.has_activity() {
+                out += &format!("{},{}\n", year, csv_row(tmp, metrics).join(","));
+            }
         }
     }
+    out
 }
 
-fn process_bdeck_files(file_list: Vec<String>) -> (Vec<StormStats>, HashMap<i32, PerBasinACE>) {
-    let mut yearly_ace_map: HashMap<i32, PerBasinACE> = HashMap::new();
+fn process_bdeck_files(
+    file_list: Vec<String>,
+) -> (Vec<StormStats>, HashMap<i32, PerBasinMetrics>, Vec<bdeck::ParseError>) {
+    let mut yearly_ace_map: HashMap<i32, PerBasinMetrics> = HashMap::new();
     let mut storm_stats: Vec<StormStats> = Vec::new();
+    let mut errors: Vec<bdeck::ParseError> = Vec::new();
     for file_path in file_list {
-        let file = fs::read_to_string(file_path).unwrap();
-        let mut last_time = "";
-        let atcf_basin = &file[0..2];
-        let atcf_number = &file[4..6];
-        let atcf_code = atcf_basin.to_owned() + atcf_number;
+        let file = match fs::read_to_string(&file_path) {
+            Ok(file) => file,
+            Err(e) => {
+                errors.push(bdeck::ParseError {
+                    path: file_path,
+                    line_number: 0,
+                    byte_range: (0, 0),
+                    reason: format!("failed to read file: {}", e),
+                });
+                continue;
+            }
+        };
+        let mut last_time = String::new();
         let mut ss_tmp = StormStats::default();
-        ss_tmp.atcf_code = atcf_code;
-        for line in file.lines() {
-            let line_time = &line[8..18];
-            if last_time == line_time {
+        // Per-basin peak wind across *every* tropical fix (not just the
+        // 6-hourly >=35kt samples ACE/PDI accumulate over), so threshold
+        // counts reflect the storm's actual peak even if it occurs at a
+        // non-synoptic hour or never reaches ACE's 35kt floor.
+        let mut storm_basin_peak: HashMap<Basin, i32> = HashMap::new();
+        let mut storm_year_basin_peak: HashMap<(i32, Basin), i32> = HashMap::new();
+        for (line_number, line) in file.lines().enumerate() {
+            let fix = match bdeck::parse_line(&file_path, line_number + 1, line) {
+                Ok(fix) => fix,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if ss_tmp.atcf_code.is_empty() {
+                ss_tmp.atcf_code = fix.atcf_basin.clone() + &fix.atcf_number;
+            }
+            if last_time == fix.time_str {
                 continue;
             }
-            last_time = line_time;
-            let mut year: i32 = (&line_time[..4]).parse().unwrap();
+            last_time = fix.time_str.clone();
+            let mut year = fix.year;
             // Handle southern hemisphere
-            let month: i32 = (&line_time[4..6]).parse().unwrap();
-            if (month > 6) & (atcf_basin == "SH") {
+            if (fix.month > 6) & (fix.atcf_basin == "SH") {
                 year += 1;
             }
-            if !yearly_ace_map.contains_key(&year) {
-                yearly_ace_map.insert(year, PerBasinACE::default());
-            }
-            let line_len = line.len() - 1;
-            let temp_wind: &str;
-            if line_len < 51 {
-                // Fix case that a space is missing in short-style bdeck
-                temp_wind = &line[line_len - 3..];
-            } else {
-                temp_wind = &line[48..51];
-            }
-            let mut wind: i32 = temp_wind
-                .strip_prefix(" ")
-                .unwrap_or(temp_wind)
-                .parse()
-                .unwrap_or(0);
-            if wind == 999 {
-                wind = 0;
-            }
+            yearly_ace_map.entry(year).or_default();
+            let wind = fix.wind;
             if wind > ss_tmp.max_wind {
                 ss_tmp.max_wind = wind;
             }
-            let lat_str = &line[35..39];
-            let lat_string: String = lat_str[..3]
-                .chars()
-                .filter(|c| !c.is_whitespace())
-                .collect();
-            let mut latitude: f32 = lat_string.parse::<f32>().unwrap() / 10.;
-            if &lat_str[3..4] == "S" {
-                latitude *= -1.
-            }
-            let lon_str = &line[41..46];
-            let lon_string: String = lon_str[..4]
-                .chars()
-                .filter(|c| !c.is_whitespace())
-                .collect();
-            let mut longitude: f32 = lon_string.parse::<f32>().unwrap() / 10.;
-            if &lon_str[4..5] == "W" {
-                longitude = 360. - longitude;
+            if is_tropical(&fix.storm_type) {
+                let basin = get_basin(&fix.atcf_basin, fix.latitude, fix.longitude);
+                let peak = storm_basin_peak.entry(basin).or_insert(0);
+                if wind > *peak {
+                    *peak = wind;
+                }
+                let year_peak = storm_year_basin_peak.entry((year, basin)).or_insert(0);
+                if wind > *year_peak {
+                    *year_peak = wind;
+                }
+                if is_synop_time(fix.hour) && wind >= 35 {
+                    ss_tmp.metrics.update_synoptic(&basin, wind);
+                    yearly_ace_map.get_mut(&year).unwrap().update_synoptic(&basin, wind);
+                }
             }
-            let mut storm_type = "";
-            if line_len > 59 {
-                // Read type of storm in long-style bdeck
-                storm_type = &line[59..61];
+        }
+        // A basin only earns a "named storm" credit (and, from there,
+        // hurricane/major) if the storm actually reached named-storm
+        // strength *in that basin*, using that basin's own peak wind.
+        for (basin, peak) in &storm_basin_peak {
+            if *peak >= 34 {
+                ss_tmp.metrics.record_storm(basin, *peak);
             }
-            if is_tropical(storm_type) & is_synop_time(&line_time[8..10]) {
-                if wind >= 35 {
-                    let basin = get_basin(latitude, longitude);
-                    let ace = wind.pow(2);
-                    ss_tmp.ace.update_ace(&basin, ace);
-                    let tmp = yearly_ace_map.get_mut(&year).unwrap();
-                    tmp.update_ace(&basin, ace);
-                }
+        }
+        for ((year, basin), peak) in &storm_year_basin_peak {
+            if *peak >= 34 {
+                yearly_ace_map.get_mut(year).unwrap().record_storm(basin, *peak);
             }
         }
-        storm_stats.push(ss_tmp);
+        // A file where every line failed to parse never produced a real
+        // storm; don't synthesize a phantom entry for it.
+        if !ss_tmp.atcf_code.is_empty() {
+            storm_stats.push(ss_tmp);
+        }
     }
-    (storm_stats, yearly_ace_map)
+    (storm_stats, yearly_ace_map, errors)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
 }
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    #[arg(value_name = "FILE")]
-    input_file: Option<String>,
+    #[arg(
+        value_name = "PATH",
+        help = "Files, directories, or glob patterns (accepts multiple)"
+    )]
+    input_paths: Vec<String>,
+
+    #[arg(
+        short = 'd',
+        long,
+        value_name = "DIR",
+        help = "Directory or pattern (accepts multiple)"
+    )]
+    input_dir: Vec<String>,
+
+    #[arg(
+        short = 'r',
+        long,
+        help = "Recurse into subdirectories when scanning a directory"
+    )]
+    recursive: bool,
+
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        default_value = "b*.dat",
+        help = "Filename glob used to select b-deck files when scanning a directory"
+    )]
+    name_pattern: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Output format"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        help = "With --format csv, append the yearly summary as a separate section"
+    )]
+    csv_split: bool,
 
-    #[arg(short = 'd', long, value_name = "DIR", help = "Directory or pattern")]
-    input_dir: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "ace",
+        help = "Metrics to include in output, comma separated (ace, pdi, storms, hurricanes, major, ntc)"
+    )]
+    metrics: Vec<Metric>,
 }
 
-fn list_files(path: String) -> Result<Vec<String>, Error> {
+/// Recursively (when `recursive` is set) collect files under `dir` whose
+/// name matches `name_pattern`, descending into subdirectories but skipping
+/// any entry that can't be read rather than aborting the whole walk.
+fn walk_dir(dir: &Path, recursive: bool, name_pattern: &Pattern, out: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if recursive {
+                walk_dir(&path, recursive, name_pattern, out);
+            }
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if name_pattern.matches(name) {
+                out.push(path.display().to_string());
+            }
+        }
+    }
+}
+
+fn list_files(path: &str, recursive: bool, name_pattern: &Pattern) -> Result<Vec<String>, Error> {
     let file_list: Vec<String>;
-    let md = fs::metadata(&path);
+    let md = fs::metadata(path);
     match md {
         Ok(md) => {
             if md.is_dir() {
-                file_list = fs::read_dir(path.strip_prefix(" ").unwrap_or(path.as_str()))
-                    .unwrap()
-                    .map(|res| res.unwrap().path().display().to_string())
-                    .collect();
+                let mut found = Vec::new();
+                walk_dir(Path::new(path), recursive, name_pattern, &mut found);
+                file_list = found;
             } else {
-                return Err(Error::new(
-                    ErrorKind::InvalidInput,
-                    format!("Not a directory: {}", path),
-                ));
+                file_list = vec![path.to_string()];
             }
         }
         Err(e) => {
-            let g = glob(&path);
+            let g = glob(path);
             match g {
-                Ok(g) => file_list = g.map(|path| path.unwrap().display().to_string()).collect(),
+                Ok(g) => file_list = g.filter_map(|path| path.ok()).map(|path| path.display().to_string()).collect(),
                 Err(_) => return Err(e),
             }
         }
@@ -261,40 +655,277 @@ fn list_files(path: String) -> Result<Vec<String>, Error> {
 
 fn main() {
     let args = Cli::parse();
-    let file_list: Vec<String>;
-    if let Some(file) = args.input_file {
-        file_list = vec![file];
-    } else {
-        if let Some(dir) = args.input_dir {
-            let files = list_files(dir);
-            match files {
-                Ok(files) => file_list = files,
-                Err(e) => {
-                    println!("{}", e);
-                    Cli::command().print_help().unwrap();
-                    return;
-                }
-            }
-        } else {
-            Cli::command().print_help().unwrap();
+    let mut input_paths = args.input_paths.clone();
+    input_paths.extend(args.input_dir.clone());
+    if input_paths.is_empty() {
+        Cli::command().print_help().unwrap();
+        return;
+    }
+    let name_pattern = match Pattern::new(&args.name_pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            println!("Invalid --name-pattern {}: {}", args.name_pattern, e);
             return;
         }
+    };
+    let mut file_list: Vec<String> = Vec::new();
+    for path in input_paths {
+        match list_files(&path, args.recursive, &name_pattern) {
+            Ok(files) => file_list.extend(files),
+            Err(e) => {
+                println!("{}", e);
+                Cli::command().print_help().unwrap();
+                return;
+            }
+        }
     }
-    if file_list.len() == 0 {
+    file_list.sort();
+    file_list.dedup();
+    if file_list.is_empty() {
         println!("No files found!");
         return;
     }
-    let (storm_stats, ace_map) = process_bdeck_files(file_list);
-    for ss in storm_stats {
-        println!(
-            "{}: {:7.4}   Max Wind: {:3}kt",
-            ss.atcf_code,
-            ss.ace.sum() as f32 / 10000.,
-            ss.max_wind
+    let (storm_stats, ace_map, errors) = process_bdeck_files(file_list);
+    if !errors.is_empty() {
+        eprintln!(
+            "Skipped {} malformed record(s) (first {} shown):",
+            errors.len(),
+            errors.len().min(5)
         );
-        if ss.ace.basin_count() > 1 {
-            ss.ace.print_perbasin_ace();
+        for e in errors.iter().take(5) {
+            eprintln!("  {}", e);
+        }
+    }
+    match args.format {
+        OutputFormat::Text => {
+            for ss in &storm_stats {
+                println!(
+                    "{}: {:7.4}   Max Wind: {:3}kt",
+                    ss.atcf_code,
+                    ss.metrics.sum_ace() as f32 / 10000.,
+                    ss.max_wind
+                );
+                if ss.metrics.basin_count() > 1 {
+                    ss.metrics.print_perbasin(&args.metrics);
+                }
+            }
+            print_ace(&ace_map, &args.metrics);
         }
+        OutputFormat::Json => println!("{}", render_json(&storm_stats, &ace_map, &args.metrics)),
+        OutputFormat::Csv => print!("{}", render_csv(&storm_stats, &ace_map, args.csv_split, &args.metrics)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isthmus_line_south_of_the_reference_band() {
+        // Below ISTHMUS_SOUTH's latitude, the boundary is that point's meridian.
+        assert!(is_east_of_isthmus(5.0, 284.0));
+        assert!(!is_east_of_isthmus(5.0, 280.0));
+    }
+
+    #[test]
+    fn isthmus_line_north_of_the_reference_band() {
+        // Above ISTHMUS_NORTH's latitude, the boundary is that point's meridian.
+        assert!(is_east_of_isthmus(20.0, 265.0));
+        assert!(!is_east_of_isthmus(20.0, 255.0));
+    }
+
+    #[test]
+    fn isthmus_line_interpolates_between_the_reference_points() {
+        // Midway in latitude between the two references, the boundary
+        // longitude should sit midway between their longitudes too.
+        let mid_lat = (ISTHMUS_SOUTH.0 + ISTHMUS_NORTH.0) / 2.0;
+        let mid_lon = (ISTHMUS_SOUTH.1 + ISTHMUS_NORTH.1) / 2.0;
+        assert!(is_east_of_isthmus(mid_lat, mid_lon + 3.0));
+        assert!(!is_east_of_isthmus(mid_lat, mid_lon - 3.0));
+    }
+
+    #[test]
+    fn geography_fallback_uses_the_isthmus_line_between_the_two_meridians() {
+        let mid_lat = (ISTHMUS_SOUTH.0 + ISTHMUS_NORTH.0) / 2.0;
+        let mid_lon = (ISTHMUS_SOUTH.1 + ISTHMUS_NORTH.1) / 2.0;
+        assert!(matches!(
+            get_basin_by_geography(mid_lat, mid_lon + 3.0),
+            Basin::ATL
+        ));
+        assert!(matches!(
+            get_basin_by_geography(mid_lat, mid_lon - 3.0),
+            Basin::EPAC
+        ));
+    }
+
+    #[test]
+    fn geography_fallback_near_the_dateline_is_unaffected_by_the_isthmus_line() {
+        // Well clear of the 240-300 isthmus band, the simple WPAC/EPAC
+        // split at 180 degrees still applies.
+        assert!(matches!(get_basin_by_geography(15.0, 179.9), Basin::WPAC));
+        assert!(matches!(get_basin_by_geography(15.0, 180.1), Basin::EPAC));
+    }
+
+    #[test]
+    fn atcf_basin_code_is_authoritative_over_geography() {
+        // Geographically this would fall on the EPAC side of the isthmus
+        // line, but an explicit "AL" code should still win.
+        assert!(matches!(get_basin("AL", 10.0, 270.0), Basin::ATL));
+        assert!(matches!(get_basin("EP", 10.0, 270.0), Basin::EPAC));
+        assert!(matches!(get_basin("CP", 10.0, 270.0), Basin::EPAC));
+        assert!(matches!(get_basin("WP", 10.0, 140.0), Basin::WPAC));
+        assert!(matches!(get_basin("IO", 10.0, 80.0), Basin::NIO));
+        assert!(matches!(get_basin("SH", -10.0, 80.0), Basin::SHEM));
+    }
+
+    #[test]
+    fn unrecognized_atcf_basin_code_falls_back_to_geography() {
+        // (10N, 270E) sits west of the isthmus line, so geography says EPAC.
+        assert!(matches!(get_basin("XX", 10.0, 270.0), Basin::EPAC));
+    }
+
+    fn sample_atl_storm() -> StormStats {
+        let mut ss = StormStats {
+            atcf_code: "AL012020".to_string(),
+            max_wind: 65,
+            ..Default::default()
+        };
+        ss.metrics.atl = BasinMetrics {
+            ace: 50000,
+            pdi: 1_000_000,
+            storms: 1,
+            hurricanes: 1,
+            major: 0,
+        };
+        ss
+    }
+
+    #[test]
+    fn render_json_emits_per_storm_and_yearly_summary() {
+        let ss = sample_atl_storm();
+        let mut ace_map: HashMap<i32, PerBasinMetrics> = HashMap::new();
+        ace_map.insert(
+            2020,
+            PerBasinMetrics {
+                atl: ss.metrics.atl,
+                ..Default::default()
+            },
+        );
+
+        let json = render_json(&[ss], &ace_map, &[Metric::Ace]);
+        assert!(json.contains("\"atcf_code\":\"AL012020\""));
+        assert!(json.contains("\"max_wind\":65"));
+        assert!(json.contains("\"ace\":5.0000"));
+        assert!(json.contains("\"per_basin_metrics\":{\"atl\":{\"ace\":5.0000}}"));
+        assert!(json.contains("\"yearly_summary\":{\"2020\":{\"atl\":{\"ace\":5.0000}}}"));
+    }
+
+    #[test]
+    fn render_csv_emits_a_row_per_storm_and_an_optional_yearly_section() {
+        let ss = sample_atl_storm();
+        let mut ace_map: HashMap<i32, PerBasinMetrics> = HashMap::new();
+        ace_map.insert(
+            2020,
+            PerBasinMetrics {
+                atl: ss.metrics.atl,
+                ..Default::default()
+            },
+        );
+
+        let csv = render_csv(&[ss], &ace_map, true, &[Metric::Ace]);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "atcf_code,max_wind,ace_wpac,ace_epac,ace_atl,ace_shem,ace_nio"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "AL012020,65,0.0000,0.0000,5.0000,0.0000,0.0000"
+        );
+        assert!(csv.contains("year,ace_wpac,ace_epac,ace_atl,ace_shem,ace_nio\n"));
+        assert!(csv.contains("2020,0.0000,0.0000,5.0000,0.0000,0.0000"));
+    }
+
+    #[test]
+    fn walk_dir_filters_by_name_pattern_and_only_recurses_when_asked() {
+        let base = std::env::temp_dir().join(format!(
+            "acecal_walk_dir_test_{}_{}",
+            std::process::id(),
+            line!()
+        ));
+        let sub = base.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(base.join("bal012020.dat"), "").unwrap();
+        fs::write(base.join("notes.txt"), "").unwrap();
+        fs::write(sub.join("bep022020.dat"), "").unwrap();
+        let pattern = Pattern::new("b*.dat").unwrap();
+
+        let mut flat = Vec::new();
+        walk_dir(&base, false, &pattern, &mut flat);
+        assert_eq!(flat.len(), 1);
+        assert!(flat[0].ends_with("bal012020.dat"));
+
+        let mut recursed = Vec::new();
+        walk_dir(&base, true, &pattern, &mut recursed);
+        assert_eq!(recursed.len(), 2);
+        assert!(recursed.iter().any(|p| p.ends_with("bal012020.dat")));
+        assert!(recursed.iter().any(|p| p.ends_with("bep022020.dat")));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    /// Build a fixed-width b-deck line at the same byte offsets `bdeck::parse_line`
+    /// reads, for tests that need to drive `process_bdeck_files` end to end.
+    fn make_bdeck_line(basin: &str, number: &str, time: &str, wind: i32, storm_type: &str) -> String {
+        let mut bytes = vec![b' '; 70];
+        bytes[0..2].copy_from_slice(basin.as_bytes());
+        bytes[4..6].copy_from_slice(number.as_bytes());
+        bytes[8..18].copy_from_slice(time.as_bytes());
+        bytes[35..38].copy_from_slice(b"200");
+        bytes[38] = b'N';
+        bytes[41..45].copy_from_slice(b"0700");
+        bytes[45] = b'W';
+        let wind_str = format!("{:>3}", wind);
+        bytes[48..51].copy_from_slice(wind_str.as_bytes());
+        bytes[59..61].copy_from_slice(storm_type.as_bytes());
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn process_bdeck_files_accumulates_pdi_and_threshold_counts_from_synoptic_fixes() {
+        let path = std::env::temp_dir().join(format!(
+            "acecal_metrics_test_{}_{}.dat",
+            std::process::id(),
+            line!()
+        ));
+        let lines = [
+            make_bdeck_line("AL", "01", "2020080100", 40, "TS"),
+            make_bdeck_line("AL", "01", "2020080106", 65, "HU"),
+            make_bdeck_line("AL", "01", "2020080112", 100, "HU"),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let (storm_stats, ace_map, errors) = process_bdeck_files(vec![path.display().to_string()]);
+        fs::remove_file(&path).unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(storm_stats.len(), 1);
+        assert_eq!(storm_stats[0].max_wind, 100);
+
+        let m = storm_stats[0].metrics.atl;
+        assert_eq!(m.ace, 40 * 40 + 65 * 65 + 100 * 100);
+        assert_eq!(m.pdi, 40i64.pow(3) + 65i64.pow(3) + 100i64.pow(3));
+        // A single storm whose peak reaches major strength still counts once
+        // in each of the storms/hurricanes/major buckets, not three times.
+        assert_eq!(m.storms, 1);
+        assert_eq!(m.hurricanes, 1);
+        assert_eq!(m.major, 1);
+
+        let year_m = ace_map.get(&2020).unwrap().atl;
+        assert_eq!(year_m.ace, m.ace);
+        assert_eq!(year_m.storms, 1);
+
+        let ntc = ntc_percent("atl", &m);
+        assert!((ntc - 18.1).abs() < 0.1, "unexpected NTC: {}", ntc);
     }
-    print_ace(ace_map);
 }